@@ -121,6 +121,23 @@ impl VirtualEventType {
             VirtualEventType::Other(ev_type) => ev_type,
         }
     }
+
+    /// The short lowercase name of this virtual type, e.g. "key", "btn", "abs" or "rel". This is
+    /// the token used for the `type` field of the structured output formats.
+    pub fn name(self) -> &'static str {
+        match self {
+            VirtualEventType::Key => VirtualEventType::KEY,
+            VirtualEventType::Button => VirtualEventType::BUTTON,
+            VirtualEventType::Other(ev_type) => match ev_type {
+                EventType::ABS => "abs",
+                EventType::REL => "rel",
+                EventType::REP => "rep",
+                EventType::SYN => "syn",
+                EventType::MSC => "msc",
+                _ => "unknown",
+            },
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Copy)]
@@ -173,6 +190,76 @@ impl fmt::Debug for Event {
     }
 }
 
+/// Selects how an event is rendered when printed, e.g. by a `--print format=...` argument.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventFormat {
+    /// The default human-readable `name:value` form, identical to the `Display` implementation.
+    Default,
+    /// One JSON object per event, with explicit fields, suitable for log ingestion and scripting.
+    Json,
+    /// A flat, space-delimited `key=value` line, suitable for grep-style pipelines.
+    Fields,
+}
+
+impl Event {
+    /// Renders this event in the requested format. `EventFormat::Default` reproduces `Display`;
+    /// the other two expose every field explicitly so that consumers can distinguish, for example,
+    /// `key:a` from `btn:left` through the `type` field.
+    pub fn format(&self, format: EventFormat) -> String {
+        match format {
+            EventFormat::Default => self.to_string(),
+            EventFormat::Json => self.format_json(),
+            EventFormat::Fields => self.format_fields(),
+        }
+    }
+
+    fn format_json(&self) -> String {
+        format!(
+            concat!(
+                "{{\"type\":\"{}\",\"code\":{},\"code_name\":\"{}\",\"value\":{},",
+                "\"previous_value\":{},\"domain\":\"{}\",\"namespace\":\"{}\"}}"
+            ),
+            escape_json(self.code.virtual_ev_type().name()),
+            self.code.code(),
+            escape_json(&ecodes::event_name(self.code)),
+            self.value,
+            self.previous_value,
+            escape_json(&format!("{:?}", self.domain)),
+            self.namespace.name(),
+        )
+    }
+
+    fn format_fields(&self) -> String {
+        format!(
+            "type={} code={} code_name={} value={} previous_value={} domain={} namespace={}",
+            self.code.virtual_ev_type().name(),
+            self.code.code(),
+            ecodes::event_name(self.code),
+            self.value,
+            self.previous_value,
+            format_args!("{:?}", self.domain),
+            self.namespace.name(),
+        )
+    }
+}
+
+/// Escapes the characters that are not allowed to appear literally inside a JSON string.
+fn escape_json(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for character in value.chars() {
+        match character {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            _ if (character as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", character as u32)),
+            _ => result.push(character),
+        }
+    }
+    result
+}
+
 
 /// Namespaces are an internal concept that is not visible to the user. They are like domains, but
 /// then on a higher level such that even a filter with an empty domain cannot match events within a
@@ -190,4 +277,17 @@ pub enum Namespace {
     /// This event was caught by an --output and shall now be sent to an output device. It is not
     /// affected by any StreamEntry.
     Output,
+}
+
+impl Namespace {
+    /// The lowercase name of this namespace, as emitted in the `namespace` field of the structured
+    /// output formats.
+    pub fn name(self) -> &'static str {
+        match self {
+            Namespace::Input => "input",
+            Namespace::User => "user",
+            Namespace::Yielded => "yielded",
+            Namespace::Output => "output",
+        }
+    }
 }
\ No newline at end of file
@@ -1,15 +1,41 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
 use std::cell::Cell;
+use std::time::{Duration, Instant};
 
 use crate::error::Context;
 use crate::range::Range;
 use crate::key::Key;
 use crate::state::{State};
-use crate::event::Event;
+use crate::event::{Event, Namespace};
+use crate::loopback::{LoopbackHandle, Token};
 use crate::subprocess;
 
-pub type Effect = Box<dyn Fn(&mut State)>;
+/// The environment in which a hook's effects run. Besides mutating the shared `State`, an effect
+/// may emit new events back into the processing stream, analogous to `--map yield`, and schedule
+/// loopback wakeups for time-based behaviour.
+pub struct EffectContext<'a> {
+    /// The event whose arrival triggered the hook. Newly generated events are merged onto it, just
+    /// like a yield map merges onto the event it matched, so they inherit its domain.
+    pub caused_by: Event,
+    /// Buffer that generated events are appended to.
+    pub events_out: &'a mut Vec<Event>,
+    /// Handle used to schedule loopback tokens for time-based effects.
+    pub loopback: &'a mut LoopbackHandle,
+}
+
+pub type Effect = Box<dyn Fn(&mut State, &mut EffectContext)>;
+
+/// The transition a `Tracker` underwent because of a single event.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TrackerChange {
+    /// The event did not change whether the tracked key is down.
+    NoChange,
+    /// The tracked key went from up to down (rising edge).
+    Activated,
+    /// The tracked key went from down to up (falling edge).
+    Released,
+}
 
 /// A tracker is used to track whether a certain key is held down. This is useful for --hook type
 /// arguments.
@@ -30,18 +56,25 @@ impl Tracker {
         }
     }
 
-    /// If the event matches, remembers whether this event falls in the desired range.
-    /// If this event falls in the desired range and the previous one didn't, returns true.
-    /// Otherwise, returns false.
-    fn apply(&self, event: &Event) -> bool {
-        if self.key.matches(event) {
-            let previous_value = self.state.get();
-            let new_value = self.range.contains(event.value);
-            self.state.set(new_value);
-            
-            new_value && ! previous_value
-        } else {
-            false
+    /// If the event matches, updates whether this key is currently down and reports the transition
+    /// that took place. KEY_REP events (value 2) repeat the current state and never change whether
+    /// the key is held, so they are treated as `NoChange`.
+    fn apply(&self, event: &Event) -> TrackerChange {
+        if ! self.key.matches(event) {
+            return TrackerChange::NoChange;
+        }
+        if event.ev_type().is_key() && event.value == 2 {
+            return TrackerChange::NoChange;
+        }
+
+        let previous_value = self.state.get();
+        let new_value = self.range.contains(event.value);
+        self.state.set(new_value);
+
+        match (previous_value, new_value) {
+            (false, true) => TrackerChange::Activated,
+            (true, false) => TrackerChange::Released,
+            _ => TrackerChange::NoChange,
         }
     }
 
@@ -50,9 +83,43 @@ impl Tracker {
     }
 }
 
+/// State of an ordered key-sequence trigger. The ordered key list is the hook's `hold_trackers`;
+/// `cursor` is the index of the next key expected, and `pending_token` arms the inter-key deadline.
+struct SequenceState {
+    /// Maximum time allowed between two successive presses before progress is reset.
+    timeout: Duration,
+    /// Index into `hold_trackers` of the next key that must be pressed.
+    cursor: Cell<usize>,
+    /// The loopback token that will reset the cursor if the next key does not arrive in time.
+    pending_token: Cell<Option<Token>>,
+}
+
 pub struct Hook {
     hold_trackers: Vec<Tracker>,
+
+    /// If set, the hook fires only when `hold_trackers` are activated in order within the sequence
+    /// timeout, rather than when all of them are simultaneously down.
+    sequence: Option<SequenceState>,
+
+    /// Effects that run when the hook triggers. Without a `hold_duration` they run as soon as all
+    /// trackers are down; with one, they run only once the keys have been held long enough.
     effects: Vec<Effect>,
+
+    /// Effects that run when the keys are tapped instead of held. Only relevant when a
+    /// `hold_duration` has been configured.
+    tap_effects: Vec<Effect>,
+
+    /// If set, the hook distinguishes a short tap from a long hold: its `effects` only run after
+    /// the keys have been held down for at least this long, and `tap_effects` run if they are
+    /// released sooner.
+    hold_duration: Option<Duration>,
+
+    /// While a hold timer is pending, the loopback token that will fire it and the moment at which
+    /// all trackers became active. Both are cleared once the window resolves into a tap or a hold.
+    pending_token: Cell<Option<Token>>,
+    activation_time: Cell<Option<Instant>>,
+    /// The event that started the current hold window, reused as `caused_by` when the hold fires.
+    activation_event: Cell<Option<Event>>,
 }
 
 impl Hook {
@@ -60,21 +127,72 @@ impl Hook {
         let hold_trackers = hold_keys.into_iter().map(
             |key| Tracker::new(key)
         ).collect();
-        Hook { hold_trackers, effects: Vec::new() }
+        Hook {
+            hold_trackers,
+            sequence: None,
+            effects: Vec::new(),
+            tap_effects: Vec::new(),
+            hold_duration: None,
+            pending_token: Cell::new(None),
+            activation_time: Cell::new(None),
+            activation_event: Cell::new(None),
+        }
     }
 
     pub fn add_effect(&mut self, effect: Effect) {
         self.effects.push(effect);
     }
 
-    fn apply(&self, event: &Event, state: &mut State) {
-        let any_tracker_activated = self.hold_trackers.iter().any(
-            |tracker| tracker.apply(event)
-        );
+    /// Adds an effect that runs when the tracked keys are tapped rather than held. Has no observable
+    /// result unless a `hold_duration` is also configured via `set_hold_duration`.
+    pub fn add_tap_effect(&mut self, effect: Effect) {
+        self.tap_effects.push(effect);
+    }
+
+    /// Turns this hook into a dual-function tap-vs-hold hook: `effects` fire only after the keys
+    /// have been held for `duration`, and `tap_effects` fire if they are released sooner.
+    pub fn set_hold_duration(&mut self, duration: Duration) {
+        self.hold_duration = Some(duration);
+    }
+
+    /// Turns this hook into an ordered key-sequence trigger: the hold keys must be activated in the
+    /// order they were given, with no more than `timeout` between successive presses, for the
+    /// effects to run. Out-of-order or too-slow presses reset progress.
+    pub fn set_sequence(&mut self, timeout: Duration) {
+        self.sequence = Some(SequenceState {
+            timeout,
+            cursor: Cell::new(0),
+            pending_token: Cell::new(None),
+        });
+    }
+
+    fn apply(&self, event: &Event, state: &mut State, events_out: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if let Some(sequence) = &self.sequence {
+            return self.apply_sequence(event, sequence, state, events_out, loopback);
+        }
+
+        let mut any_activated = false;
+        let mut any_released = false;
+        for tracker in &self.hold_trackers {
+            match tracker.apply(event) {
+                TrackerChange::Activated => any_activated = true,
+                TrackerChange::Released => any_released = true,
+                TrackerChange::NoChange => (),
+            }
+        }
+
+        // A tracked key was released while a hold timer was pending: this resolves the window as a
+        // tap. Cancel the timer (by forgetting its token, so its wakeup is ignored) and run the tap
+        // effects.
+        if any_released && self.pending_token.get().is_some() {
+            self.clear_pending();
+            self.fire(&self.tap_effects, *event, state, events_out, loopback);
+            return;
+        }
 
         // Check whether at least one tracker turned active that wasn't on active,
         // i.e. whether this event contributed to the filters of this hook.
-        if ! any_tracker_activated {
+        if ! any_activated {
             return;
         }
 
@@ -84,27 +202,139 @@ impl Hook {
                 return;
             }
         }
-        self.apply_effects(state);
+
+        match self.hold_duration {
+            // Ordinary hook: fire immediately now that all trackers are down.
+            None => self.fire(&self.effects, *event, state, events_out, loopback),
+            // Dual-function hook: wait for the hold timer. A re-press before it fires reschedules
+            // the window, and the stale token is ignored once a newer one is stored.
+            Some(duration) => {
+                let token = loopback.schedule_wakeup(duration);
+                self.pending_token.set(Some(token));
+                self.activation_time.set(Some(Instant::now()));
+                self.activation_event.set(Some(*event));
+            }
+        }
     }
 
-    fn apply_effects(&self, state: &mut State) {
-        for effect in &self.effects {
-            effect(state);
+    /// Advances the ordered key-sequence state machine by one event. The key at the cursor advances
+    /// progress; any other rising edge resets the cursor to zero (restarting immediately if the
+    /// out-of-order key happens to be the first key of the sequence).
+    fn apply_sequence(&self, event: &Event, sequence: &SequenceState, state: &mut State, events_out: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let cursor = sequence.cursor.get();
+        let mut activated_at_cursor = false;
+        let mut activated_elsewhere = false;
+        let mut first_key_activated = false;
+
+        for (index, tracker) in self.hold_trackers.iter().enumerate() {
+            if tracker.apply(event) == TrackerChange::Activated {
+                if index == cursor {
+                    activated_at_cursor = true;
+                } else {
+                    activated_elsewhere = true;
+                }
+                if index == 0 {
+                    first_key_activated = true;
+                }
+            }
+        }
+
+        if activated_at_cursor {
+            self.sequence_advance(sequence, *event, state, events_out, loopback);
+        } else if activated_elsewhere {
+            sequence.cursor.set(0);
+            sequence.pending_token.set(None);
+            if first_key_activated {
+                self.sequence_advance(sequence, *event, state, events_out, loopback);
+            }
         }
     }
 
-    pub fn apply_to_all(&self, events: &[Event], state: &mut State) {
+    /// Advances the cursor after a matching press. Firing the effects once the last key is reached
+    /// and otherwise rearming the inter-key deadline; the superseded token is ignored once replaced.
+    fn sequence_advance(&self, sequence: &SequenceState, caused_by: Event, state: &mut State, events_out: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let next = sequence.cursor.get() + 1;
+        if next >= self.hold_trackers.len() {
+            sequence.cursor.set(0);
+            sequence.pending_token.set(None);
+            self.fire(&self.effects, caused_by, state, events_out, loopback);
+        } else {
+            sequence.cursor.set(next);
+            sequence.pending_token.set(Some(loopback.schedule_wakeup(sequence.timeout)));
+        }
+    }
+
+    /// Resolves a pending hold timer. If the token matches the one we are waiting on and every
+    /// tracked key is still down after the full duration, the hold effects run; otherwise the
+    /// wakeup is a stale leftover and is ignored. Mirrors `Withhold::wakeup`.
+    pub fn wakeup(&self, token: &Token, state: &mut State, events_out: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        // An ordered sequence hook only uses its token to time out and reset in-progress input.
+        if let Some(sequence) = &self.sequence {
+            if sequence.pending_token.get().as_ref() == Some(token) {
+                sequence.cursor.set(0);
+                sequence.pending_token.set(None);
+            }
+            return;
+        }
+
+        if self.pending_token.get().as_ref() != Some(token) {
+            return;
+        }
+
+        let held_long_enough = match (self.hold_duration, self.activation_time.get()) {
+            (Some(duration), Some(since)) => since.elapsed() >= duration,
+            _ => false,
+        };
+        let caused_by = self.activation_event.get();
+        self.clear_pending();
+
+        if held_long_enough && self.hold_trackers.iter().all(Tracker::is_down) {
+            if let Some(caused_by) = caused_by {
+                self.fire(&self.effects, caused_by, state, events_out, loopback);
+            }
+        }
+    }
+
+    fn clear_pending(&self) {
+        self.pending_token.set(None);
+        self.activation_time.set(None);
+        self.activation_event.set(None);
+    }
+
+    fn fire(&self, effects: &[Effect], caused_by: Event, state: &mut State, events_out: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let mut context = EffectContext { caused_by, events_out, loopback };
+        for effect in effects {
+            effect(state, &mut context);
+        }
+    }
+
+    pub fn apply_to_all(&self, events: &[Event], state: &mut State, events_out: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
         for event in events {
-            self.apply(event, state);
+            self.apply(event, state, events_out, loopback);
         }
     }
 
     /// Makes this hook invoke an external subprocess when this hook is triggered.
     pub fn add_command(&mut self, program: String, args: Vec<String>) {
         self.add_effect(
-            Box::new(move |_| {
+            Box::new(move |_, _| {
                 subprocess::try_spawn(program.clone(), args.clone()).print_err();
             })
         );
     }
-}
\ No newline at end of file
+
+    /// Makes this hook emit synthetic events when it is triggered. The keys are merged onto the
+    /// event that triggered the hook and sent through the `Namespace::Yielded` path, so they bypass
+    /// any upstream `StreamEntry` just like the events produced by `--map yield`.
+    pub fn add_send_keys(&mut self, keys: Vec<Key>) {
+        self.add_effect(
+            Box::new(move |_, context| {
+                for key in &keys {
+                    let mut event = key.merge(context.caused_by);
+                    event.namespace = Namespace::Yielded;
+                    context.events_out.push(event);
+                }
+            })
+        );
+    }
+}
@@ -1,5 +1,7 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
+use std::time::Duration;
+
 use crate::event::{Event, Channel, EventFlag};
 use crate::key::Key;
 use crate::loopback::{LoopbackHandle, Token};
@@ -13,6 +15,10 @@ pub struct Withhold {
     /// Only withhold events that match one of the following keys.
     keys: Vec<Key>,
 
+    /// If set, a withheld press that is never consumed by a trigger is released after this long
+    /// instead of being withheld indefinitely.
+    max_withhold_duration: Option<Duration>,
+
     channel_state: Vec<(Channel, ChannelState)>,
 }
 
@@ -20,10 +26,17 @@ impl Withhold {
     pub fn new(keys: Vec<Key>, triggers: Vec<Trigger>) -> Withhold {
         Withhold {
             keys, triggers,
+            max_withhold_duration: None,
             channel_state: Vec::new(),
         }
     }
 
+    /// Sets a maximum duration for which a press may be withheld. After this long, an unconsumed
+    /// press is released as if no trigger had ever been interested in it.
+    pub fn set_max_withhold_duration(&mut self, duration: Duration) {
+        self.max_withhold_duration = Some(duration);
+    }
+
     pub fn apply_to_all(&mut self, events: &[Event], events_out: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
         for event in events {
             self.apply(*event, events_out, loopback);
@@ -65,14 +78,19 @@ impl Withhold {
                 .map(|(_channel, state)| state);
 
             if event.value == 1 {
+                // Arm the withhold timeout, if one is configured, so that an unconsumed press does
+                // not stay withheld forever. An already-withheld channel keeps its existing token.
+                let timeout_token = self.max_withhold_duration
+                    .map(|duration| loopback.schedule_wakeup(duration));
+
                 // Withhold the event. If there are no active trackers withholding this event,
                 // it will be released later at `self.release_events()`.
                 match current_channel_state {
                     None => self.channel_state.push(
-                        (event.channel(), ChannelState::Withheld { withheld_event: event })
+                        (event.channel(), ChannelState::Withheld { withheld_event: event, timeout_token })
                     ),
                     Some(state @ &mut ChannelState::Residual) => {
-                        *state = ChannelState::Withheld { withheld_event: event }
+                        *state = ChannelState::Withheld { withheld_event: event, timeout_token }
                     },
                     Some(ChannelState::Withheld { .. }) => {},
                 }
@@ -120,6 +138,10 @@ impl Withhold {
     }
 
     pub fn wakeup(&mut self, token: &Token, events_out: &mut Vec<Event>) {
+        // A per-channel withhold timeout may have fired: release the matching press if it is still
+        // withheld and was never promoted to Residual by a trigger.
+        self.release_timed_out(token, events_out);
+
         let mut some_tracker_expired = false;
         for trigger in &mut self.triggers {
             if trigger.wakeup(token) {
@@ -136,11 +158,27 @@ impl Withhold {
         self.release_events(events_out);
     }
 
+    /// Releases the press of the channel whose withhold timeout this token represents. Releasing it
+    /// in place keeps `channel_state` ordering intact, so the withheld press is still emitted before
+    /// any later same-channel release. A token for a channel that is no longer `Withheld` (because a
+    /// trigger already promoted it to `Residual`) is a stale leftover and is ignored.
+    fn release_timed_out(&mut self, token: &Token, events_out: &mut Vec<Event>) {
+        self.channel_state.retain(|(_channel, state)| {
+            if let ChannelState::Withheld { withheld_event, timeout_token: Some(deadline) } = state {
+                if deadline == token {
+                    events_out.push(*withheld_event);
+                    return false;
+                }
+            }
+            true
+        });
+    }
+
     /// Writes all events that are not withheld by any trigger to the output stream.
     fn release_events(&mut self, events_out: &mut Vec<Event>) {
         let triggers = &self.triggers;
         self.channel_state.retain(|(channel, state)| {
-            if let ChannelState::Withheld { withheld_event } = state {
+            if let ChannelState::Withheld { withheld_event, .. } = state {
                 let is_still_withheld = triggers.iter().any(|trigger|
                     trigger.has_active_tracker_matching_channel(*channel)
                 );
@@ -157,6 +195,11 @@ impl Withhold {
 // TODO: Doccomment.
 #[derive(Debug, Clone, Copy)]
 enum ChannelState {
-    Withheld { withheld_event: Event },
+    Withheld {
+        withheld_event: Event,
+        /// The loopback token that will release this press if no trigger consumes it first. `None`
+        /// when no maximum withhold duration is configured.
+        timeout_token: Option<Token>,
+    },
     Residual,
 }